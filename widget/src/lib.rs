@@ -1,9 +1,8 @@
-use serde_json::Error;
-
 use schemars::JsonSchema;
 use serde::Deserialize;
 use time::format_description;
 use time::OffsetDateTime;
+use time::PrimitiveDateTime;
 use time_humanize::Accuracy;
 use time_humanize::HumanTime;
 use time_humanize::Tense;
@@ -19,6 +18,19 @@ wit_bindgen::generate!({
 struct FromData {
     #[serde(with = "time::serde::iso8601")]
     departure: OffsetDateTime,
+    delay: Option<i32>,
+    platform: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct JourneyData {
+    category: String,
+    number: String,
+}
+
+#[derive(Deserialize)]
+struct SectionData {
+    journey: Option<JourneyData>,
 }
 
 #[derive(Deserialize)]
@@ -34,6 +46,9 @@ struct ToMetaData {
 #[derive(Deserialize)]
 struct ConnectionData {
     from: FromData,
+    sections: Vec<SectionData>,
+    capacity1st: Option<u8>,
+    capacity2nd: Option<u8>,
 }
 
 #[derive(Deserialize)]
@@ -43,16 +58,104 @@ struct PublicTransportData {
     to: ToMetaData,
 }
 
+#[derive(Deserialize)]
+struct StationboardStopData {
+    #[serde(with = "time::serde::iso8601")]
+    departure: OffsetDateTime,
+}
+
+#[derive(Deserialize)]
+struct StationboardEntryData {
+    stop: StationboardStopData,
+    name: String,
+    to: String,
+}
+
+#[derive(Deserialize)]
+struct StationboardData {
+    stationboard: Vec<StationboardEntryData>,
+}
+
 #[derive(JsonSchema, Deserialize)]
-struct Connection {
-    from_station: String,
-    to_station: String,
-    num_connections: u8,
+#[serde(tag = "type")]
+enum Connection {
+    Connection {
+        from_station: String,
+        to_station: String,
+        num_connections: u8,
+        after_time: Option<String>,
+        offset_minutes: Option<u32>,
+    },
+    Stationboard {
+        station: String,
+        num_departures: u8,
+        transportations: Option<Vec<String>>,
+    },
 }
 
 #[derive(JsonSchema, Deserialize)]
 struct WidgetConfig {
     connections: Vec<Connection>,
+    max_retry_attempts: Option<u8>,
+    #[serde(default)]
+    show_occupancy: bool,
+}
+
+const DEFAULT_MAX_RETRY_ATTEMPTS: u8 = 3;
+const OCCUPANCY_GLYPHS: [char; 4] = ['\u{2581}', '\u{2583}', '\u{2585}', '\u{2587}'];
+const MAX_ERROR_LEN: usize = 40;
+
+#[derive(Debug)]
+enum WidgetError {
+    Network,
+    HttpStatus(u16),
+    Decode(String),
+    NoDepartures(String),
+}
+
+impl std::fmt::Display for WidgetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WidgetError::Network => write!(f, "Failed to make network request"),
+            WidgetError::HttpStatus(status) => write!(f, "Response status != 200: {}", status),
+            WidgetError::Decode(message) => write!(f, "Failed to parse response: {}", message),
+            WidgetError::NoDepartures(header) => {
+                // Reserve room for the fixed suffix so `run()`'s blanket length cap never clips
+                // it off a long station/route header, leaving a half-cut name with no
+                // indication anything went wrong (see chunk0-3 review).
+                const SUFFIX: &str = "\nNo departures";
+                let header = MyWidget::truncate_error(header, MAX_ERROR_LEN - SUFFIX.len());
+                write!(f, "{}{}", header, SUFFIX)
+            }
+        }
+    }
+}
+
+// Two different anchor semantics share this type: `Instant` compares true instants and is
+// offset-agnostic (used when the user gave no literal time), while `Local` compares wall-clock
+// fields only and must never be mixed with an `OffsetDateTime` subtraction (used once a literal
+// `after_time` HH:MM is in play). See `MyWidget::query_anchor`.
+enum DepartureAnchor {
+    Instant(OffsetDateTime),
+    Local(PrimitiveDateTime),
+}
+
+impl DepartureAnchor {
+    fn as_offset_datetime(&self) -> OffsetDateTime {
+        match self {
+            DepartureAnchor::Instant(instant) => *instant,
+            DepartureAnchor::Local(local) => local.assume_utc(),
+        }
+    }
+
+    fn is_before(&self, departure: OffsetDateTime) -> bool {
+        match self {
+            DepartureAnchor::Instant(instant) => *instant < departure,
+            DepartureAnchor::Local(local) => {
+                *local < PrimitiveDateTime::new(departure.date(), departure.time())
+            }
+        }
+    }
 }
 
 const WIDGET_NAME: &str = "Public Transport";
@@ -71,14 +174,37 @@ impl Guest for MyWidget {
             };
         }
 
-        let config: WidgetConfig =
-            serde_json::from_str(&context.config).expect("Failed to parse config");
+        // `Connection` is tagged (`type: "Connection" | "Stationboard"`), so a config saved
+        // before the stationboard mode was added no longer deserializes; render that as an
+        // error instead of trapping the whole widget.
+        let config: WidgetConfig = match serde_json::from_str(&context.config) {
+            Ok(config) => config,
+            Err(error) => {
+                return WidgetResult {
+                    data: MyWidget::truncate_error(&format!("Invalid config: {}", error), MAX_ERROR_LEN),
+                };
+            }
+        };
+
+        let max_retry_attempts = config
+            .max_retry_attempts
+            .unwrap_or(DEFAULT_MAX_RETRY_ATTEMPTS);
 
         let text_buffer = config.connections.iter()
             .map(|connection| {
-                match MyWidget::fetch_connection(connection) {
+                let result = match connection {
+                    Connection::Connection { .. } => MyWidget::fetch_connection(
+                        connection,
+                        max_retry_attempts,
+                        config.show_occupancy,
+                    ),
+                    Connection::Stationboard { .. } => {
+                        MyWidget::fetch_stationboard(connection, max_retry_attempts)
+                    }
+                };
+                match result {
                     Ok(content) => content,
-                    Err(error) => error.data
+                    Err(error) => MyWidget::truncate_error(&error.to_string(), MAX_ERROR_LEN),
                 }
             })
             .collect::<Vec<_>>()
@@ -109,65 +235,272 @@ impl MyWidget {
         OffsetDateTime::from_unix_timestamp(now.seconds as i64).unwrap()
     }
 
-    pub fn fetch_connection(connection: &Connection) -> Result<String, WidgetResult> {
-        let url = format!(
+    pub fn fetch_connection(
+        connection: &Connection,
+        max_attempts: u8,
+        show_occupancy: bool,
+    ) -> Result<String, WidgetError> {
+        let Connection::Connection {
+            from_station,
+            to_station,
+            num_connections,
+            after_time,
+            offset_minutes,
+        } = connection
+        else {
+            return Err(WidgetError::Decode("Not a connection config".into()));
+        };
+
+        let anchor = MyWidget::query_anchor(MyWidget::now(), after_time.as_deref(), *offset_minutes);
+
+        let mut url = format!(
                 "http://transport.opendata.ch/v1/connections?from={}&to={}&limit=16",
-                urlencoding::encode(connection.from_station.as_str()),
-                urlencoding::encode(connection.to_station.as_str()),
+                urlencoding::encode(from_station.as_str()),
+                urlencoding::encode(to_station.as_str()),
             );
 
-            let response = http::request(http::Method::Get, url.as_str(), None);
-            let Ok(response) = response else {
-                return Err(WidgetResult {
-                    data: "Failed to make network request".into(),
-                });
-            };
+            if after_time.is_some() || offset_minutes.is_some() {
+                let anchor_instant = anchor.as_offset_datetime();
+                url += &format!(
+                    "&date={}&time={}",
+                    MyWidget::format_date(anchor_instant),
+                    MyWidget::format_time(anchor_instant)
+                );
+            }
 
-            if 200 != response.status {
-                return Err(WidgetResult {
-                    data: format!("Response status != 200: {}", response.status),
-                });
+            let response = MyWidget::request_with_retry(url.as_str(), max_attempts)?;
+
+            let data: PublicTransportData = serde_json::from_slice(response.bytes.as_slice())
+                .map_err(|error| WidgetError::Decode(error.to_string()))?;
+            MyWidget::get_departure_string(&data, *num_connections as usize, show_occupancy, anchor)
+    }
+
+    // `after_time` is what the user typed (station-local HH:MM); `now()` only ever carries a
+    // UTC offset, so replacing its hour/minute with the literal `after_time` value and then
+    // subtracting absolute instants would compare across two different offsets and silently
+    // misfilter departures by the local UTC offset (see chunk0-6 review). When `after_time` is
+    // set we instead anchor on wall-clock fields only, comparing them against
+    // `OffsetDateTime::date`/`time`, which already return the literal (non-UTC-normalized)
+    // fields the opendata.ch API reports — no offset math involved. Without `after_time` there
+    // is no literal wall-clock input to begin with, so we keep comparing true instants, which is
+    // offset-agnostic and correct.
+    pub fn query_anchor(
+        now: OffsetDateTime,
+        after_time: Option<&str>,
+        offset_minutes: Option<u32>,
+    ) -> DepartureAnchor {
+        if let Some(after_time) = after_time {
+            let now = PrimitiveDateTime::new(now.date(), now.time());
+            let format = format_description::parse("[hour]:[minute]").unwrap();
+            if let Ok(parsed) = time::Time::parse(after_time, &format) {
+                let mut anchor = now.replace_time(parsed);
+                if anchor < now {
+                    anchor += time::Duration::days(1);
+                }
+                if let Some(offset_minutes) = offset_minutes {
+                    anchor += time::Duration::minutes(offset_minutes as i64);
+                }
+                return DepartureAnchor::Local(anchor);
             }
+        }
 
-            let data: Result<PublicTransportData, Error> =
-                serde_json::from_slice(response.bytes.as_slice());
-            if let Err(error) = data {
-                return Err(WidgetResult {
-                    data: format!("Failed to parse response: {:?}", error),
+        let mut anchor = now;
+        if let Some(offset_minutes) = offset_minutes {
+            anchor += time::Duration::minutes(offset_minutes as i64);
+        }
+        DepartureAnchor::Instant(anchor)
+    }
+
+    pub fn format_date(instant: OffsetDateTime) -> String {
+        let format = format_description::parse("[year]-[month]-[day]").unwrap();
+        match instant.format(&format) {
+            Ok(date) => date,
+            Err(_) => "Could not format date".to_string(),
+        }
+    }
+
+    pub fn fetch_stationboard(
+        connection: &Connection,
+        max_attempts: u8,
+    ) -> Result<String, WidgetError> {
+        let Connection::Stationboard { station, num_departures, transportations } = connection else {
+            return Err(WidgetError::Decode("Not a stationboard config".into()));
+        };
+
+        let mut url = format!(
+            "http://transport.opendata.ch/v1/stationboard?station={}&limit={}",
+            urlencoding::encode(station.as_str()),
+            num_departures,
+        );
+
+        if let Some(transportations) = transportations {
+            for transportation in transportations {
+                url += &format!(
+                    "&transportations[]={}",
+                    urlencoding::encode(transportation.as_str())
+                );
+            }
+        }
+
+        let response = MyWidget::request_with_retry(url.as_str(), max_attempts)?;
+
+        let data: StationboardData = serde_json::from_slice(response.bytes.as_slice())
+            .map_err(|error| WidgetError::Decode(error.to_string()))?;
+        MyWidget::get_stationboard_string(station, &data)
+    }
+
+    pub fn request_with_retry(url: &str, max_attempts: u8) -> Result<http::Response, WidgetError> {
+        let max_attempts = max_attempts.max(1);
+        let mut attempt = 1;
+        loop {
+            let result = http::request(http::Method::Get, url, None)
+                .map_err(|_| WidgetError::Network)
+                .and_then(|response| {
+                    if 200 == response.status {
+                        Ok(response)
+                    } else {
+                        Err(WidgetError::HttpStatus(response.status))
+                    }
                 });
-            };
-            let data = data.unwrap();
-            Ok(MyWidget::get_departure_string(&data, connection.num_connections as usize))
 
+            if attempt >= max_attempts || !MyWidget::is_transient(&result) {
+                return result;
+            }
+
+            const MAX_BACKOFF_EXPONENT: u8 = 6;
+            let backoff_exponent = (attempt - 1).min(MAX_BACKOFF_EXPONENT);
+            MyWidget::wait_seconds(1u64 << backoff_exponent);
+            attempt += 1;
+        }
     }
 
-    pub fn get_departure_string(data: &PublicTransportData, num_departures: usize) -> String {
-        let mut content = format!("{} -> {}", data.from.name, data.to.name);
+    fn is_transient(result: &Result<http::Response, WidgetError>) -> bool {
+        match result {
+            Err(WidgetError::Network) => true,
+            Err(WidgetError::HttpStatus(status)) => *status == 429 || *status >= 500,
+            _ => false,
+        }
+    }
+
+    fn wait_seconds(seconds: u64) {
+        let deadline = clocks::now().seconds + seconds;
+        while clocks::now().seconds < deadline {}
+    }
 
-        if data.connections.is_empty() {
-            content += "\nNo departures";
-            return content;
+    pub fn truncate_error(message: &str, max_len: usize) -> String {
+        if message.chars().count() <= max_len {
+            return message.to_string();
         }
+        let truncated: String = message.chars().take(max_len.saturating_sub(1)).collect();
+        format!("{}\u{2026}", truncated)
+    }
 
-        let connections = data
+    pub fn get_departure_string(
+        data: &PublicTransportData,
+        num_departures: usize,
+        show_occupancy: bool,
+        anchor: DepartureAnchor,
+    ) -> Result<String, WidgetError> {
+        let mut content = format!("{} -> {}", data.from.name, data.to.name);
+
+        let mut connections = data
             .connections
             .iter()
-            .filter(|connection| (connection.from.departure - MyWidget::now()).is_positive())
-            .take(num_departures);
+            .filter(|connection| anchor.is_before(MyWidget::real_departure(&connection.from)))
+            .take(num_departures)
+            .peekable();
+
+        if connections.peek().is_none() {
+            return Err(WidgetError::NoDepartures(content));
+        }
 
         for connection in connections {
-            let departure = connection.from.departure;
+            let real_departure = MyWidget::real_departure(&connection.from);
+            let mut line = String::new();
+            if let Some(label) = MyWidget::line_label(connection) {
+                line += &label;
+                line += "  ";
+            }
+            line += &MyWidget::format_departure_time(&connection.from);
+            if let Some(platform) = &connection.from.platform {
+                line += &format!("  Pl. {}", platform);
+            }
+            line += &format!(" ({})", MyWidget::format_departure_offset(real_departure));
+            if show_occupancy {
+                if let Some(occupancy) = MyWidget::format_occupancy(connection) {
+                    line += " ";
+                    line += &occupancy;
+                }
+            }
+            content += "\n";
+            content += &line;
+        }
+        Ok(content)
+    }
+
+    pub fn format_occupancy(connection: &ConnectionData) -> Option<String> {
+        if connection.capacity1st.is_none() && connection.capacity2nd.is_none() {
+            return None;
+        }
+        let glyph = |capacity: Option<u8>| match capacity {
+            Some(capacity) => OCCUPANCY_GLYPHS[capacity.min(3) as usize],
+            None => '?',
+        };
+        Some(format!(
+            "{}{}",
+            glyph(connection.capacity1st),
+            glyph(connection.capacity2nd)
+        ))
+    }
+
+    pub fn line_label(connection: &ConnectionData) -> Option<String> {
+        let journey = connection.sections.iter().find_map(|s| s.journey.as_ref())?;
+        // One-letter categories append the number directly (S3), longer ones use a space (IC 1).
+        if journey.category.len() == 1 {
+            Some(format!("{}{}", journey.category, journey.number))
+        } else {
+            Some(format!("{} {}", journey.category, journey.number))
+        }
+    }
+
+    pub fn real_departure(from: &FromData) -> OffsetDateTime {
+        match from.delay {
+            Some(delay) => from.departure + time::Duration::minutes(delay as i64),
+            None => from.departure,
+        }
+    }
+
+    pub fn get_stationboard_string(
+        station: &str,
+        data: &StationboardData,
+    ) -> Result<String, WidgetError> {
+        let mut content = station.to_string();
+
+        let mut entries = data
+            .stationboard
+            .iter()
+            .filter(|entry| (entry.stop.departure - MyWidget::now()).is_positive())
+            .peekable();
+
+        if entries.peek().is_none() {
+            return Err(WidgetError::NoDepartures(content));
+        }
+
+        for entry in entries {
+            let departure = entry.stop.departure;
             content += &format!(
-                "\n{} ({})",
-                MyWidget::format_departure_offset(departure),
-                MyWidget::format_departure_time(departure)
-            )
-            .to_string();
+                "\n{} \u{2192} {}  {} ({})",
+                entry.name,
+                entry.to,
+                MyWidget::format_time(departure),
+                MyWidget::format_departure_offset(departure)
+            );
         }
-        content
+
+        Ok(content)
     }
 
-    pub fn format_departure_time(departure: OffsetDateTime) -> String {
+    pub fn format_time(departure: OffsetDateTime) -> String {
         let format = format_description::parse("[hour]:[minute]").unwrap();
         match departure.format(&format) {
             Ok(departure) => departure,
@@ -175,6 +508,14 @@ impl MyWidget {
         }
     }
 
+    pub fn format_departure_time(from: &FromData) -> String {
+        let time = MyWidget::format_time(MyWidget::real_departure(from));
+        match from.delay {
+            Some(delay) if delay != 0 => format!("{} +{}'", time, delay),
+            _ => time,
+        }
+    }
+
     pub fn format_departure_offset(departure: OffsetDateTime) -> String {
         let departure_offset = departure - MyWidget::now();
         HumanTime::from(departure_offset.unsigned_abs()).to_text_en(Accuracy::Rough, Tense::Future)
@@ -182,3 +523,153 @@ impl MyWidget {
 }
 
 export!(MyWidget);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    fn section(category: &str, number: &str) -> SectionData {
+        SectionData {
+            journey: Some(JourneyData {
+                category: category.into(),
+                number: number.into(),
+            }),
+        }
+    }
+
+    fn connection_data(
+        sections: Vec<SectionData>,
+        capacity1st: Option<u8>,
+        capacity2nd: Option<u8>,
+    ) -> ConnectionData {
+        ConnectionData {
+            from: FromData {
+                departure: OffsetDateTime::UNIX_EPOCH,
+                delay: None,
+                platform: None,
+            },
+            sections,
+            capacity1st,
+            capacity2nd,
+        }
+    }
+
+    #[test]
+    fn line_label_uses_a_space_for_multi_letter_categories() {
+        let connection = connection_data(vec![section("IC", "1")], None, None);
+        assert_eq!(MyWidget::line_label(&connection), Some("IC 1".to_string()));
+    }
+
+    #[test]
+    fn line_label_concatenates_single_letter_categories() {
+        let connection = connection_data(vec![section("S", "3")], None, None);
+        assert_eq!(MyWidget::line_label(&connection), Some("S3".to_string()));
+    }
+
+    #[test]
+    fn line_label_is_none_without_a_journey() {
+        let connection = connection_data(vec![SectionData { journey: None }], None, None);
+        assert_eq!(MyWidget::line_label(&connection), None);
+    }
+
+    #[test]
+    fn format_occupancy_is_none_when_both_capacities_are_null() {
+        let connection = connection_data(vec![], None, None);
+        assert_eq!(MyWidget::format_occupancy(&connection), None);
+    }
+
+    #[test]
+    fn format_occupancy_renders_both_classes() {
+        let connection = connection_data(vec![], Some(0), Some(3));
+        assert_eq!(
+            MyWidget::format_occupancy(&connection),
+            Some("\u{2581}\u{2587}".to_string())
+        );
+    }
+
+    #[test]
+    fn format_occupancy_marks_an_unknown_class_with_a_question_mark() {
+        let connection = connection_data(vec![], Some(1), None);
+        assert_eq!(
+            MyWidget::format_occupancy(&connection),
+            Some("\u{2583}?".to_string())
+        );
+    }
+
+    #[test]
+    fn is_transient_retries_network_and_server_errors() {
+        assert!(MyWidget::is_transient(&Err(WidgetError::Network)));
+        assert!(MyWidget::is_transient(&Err(WidgetError::HttpStatus(429))));
+        assert!(MyWidget::is_transient(&Err(WidgetError::HttpStatus(503))));
+    }
+
+    #[test]
+    fn is_transient_treats_client_errors_as_terminal() {
+        assert!(!MyWidget::is_transient(&Err(WidgetError::HttpStatus(404))));
+        assert!(!MyWidget::is_transient(&Err(WidgetError::Decode(
+            "bad".into()
+        ))));
+    }
+
+    #[test]
+    fn truncate_error_adds_an_ellipsis_past_the_limit() {
+        let long_message = "a".repeat(60);
+        let truncated = MyWidget::truncate_error(&long_message, MAX_ERROR_LEN);
+        assert_eq!(truncated.chars().count(), MAX_ERROR_LEN);
+        assert!(truncated.ends_with('\u{2026}'));
+    }
+
+    #[test]
+    fn truncate_error_keeps_short_messages_untouched() {
+        assert_eq!(MyWidget::truncate_error("short", MAX_ERROR_LEN), "short");
+    }
+
+    #[test]
+    fn no_departures_display_reserves_room_for_the_suffix() {
+        let header = "a".repeat(60);
+        let rendered = WidgetError::NoDepartures(header).to_string();
+        assert_eq!(rendered.chars().count(), MAX_ERROR_LEN);
+        assert!(rendered.ends_with("\nNo departures"));
+    }
+
+    #[test]
+    fn query_anchor_rolls_the_after_time_to_the_next_day_once_passed() {
+        let now = datetime!(2026-07-27 18:30 UTC);
+        let anchor = MyWidget::query_anchor(now, Some("06:00"), None);
+        match anchor {
+            DepartureAnchor::Local(local) => {
+                assert_eq!(local.date(), now.date().next_day().unwrap());
+                assert_eq!(local.hour(), 6);
+                assert_eq!(local.minute(), 0);
+            }
+            DepartureAnchor::Instant(_) => panic!("expected a Local anchor"),
+        }
+    }
+
+    #[test]
+    fn query_anchor_keeps_after_time_on_the_same_day_when_still_upcoming() {
+        let now = datetime!(2026-07-27 06:00 UTC);
+        let anchor = MyWidget::query_anchor(now, Some("18:30"), None);
+        match anchor {
+            DepartureAnchor::Local(local) => {
+                assert_eq!(local.date(), now.date());
+                assert_eq!(local.hour(), 18);
+                assert_eq!(local.minute(), 30);
+            }
+            DepartureAnchor::Instant(_) => panic!("expected a Local anchor"),
+        }
+    }
+
+    #[test]
+    fn query_anchor_without_after_time_compares_true_instants() {
+        let now = datetime!(2026-07-27 06:00 UTC);
+        let anchor = MyWidget::query_anchor(now, None, Some(30));
+        match anchor {
+            DepartureAnchor::Instant(instant) => {
+                assert_eq!(instant, now + time::Duration::minutes(30));
+            }
+            DepartureAnchor::Local(_) => panic!("expected an Instant anchor"),
+        }
+    }
+}